@@ -1,9 +1,10 @@
 //! Future types.
 
-use super::BoringSSLConfig;
+use super::{AddConnectionInfo, BoringSSLConfig, BoringSSLStream};
 use futures_util::future::BoxFuture;
 use pin_project_lite::pin_project;
 use std::io::{Error, ErrorKind};
+use std::sync::Arc;
 use std::time::Duration;
 use std::{
     fmt,
@@ -13,6 +14,7 @@ use std::{
     task::{Context, Poll},
 };
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio::time::{timeout, Timeout};
 
 use boring::ssl::Ssl;
@@ -28,10 +30,18 @@ pin_project! {
 }
 
 impl<F, I, S> BoringSSLSSLAcceptorFuture<F, I, S> {
-    pub(crate) fn new(future: F, config: BoringSSLConfig, handshake_timeout: Duration) -> Self {
+    pub(crate) fn new(
+        future: F,
+        config: BoringSSLConfig,
+        handshake_timeout: Duration,
+        connection_limit: Option<Arc<Semaphore>>,
+        handshake_limit: Option<Arc<Semaphore>>,
+    ) -> Self {
         let inner = AcceptFuture::InnerAccepting {
             future,
             handshake_timeout,
+            connection_limit,
+            handshake_limit,
         };
         let config = Some(config);
 
@@ -54,12 +64,19 @@ pin_project! {
             #[pin]
             future: F,
             handshake_timeout: Duration,
+            connection_limit: Option<Arc<Semaphore>>,
+            handshake_limit: Option<Arc<Semaphore>>,
         },
         // We are waiting for TLS to install into the channel so that we can
         // proceed to return the SslStream.
         TlsAccepting {
             #[pin]
-            future: Timeout<BoxFuture<'static, Result<SslStream<I>, HandshakeError<I>>>>,
+            future: Timeout<
+                BoxFuture<
+                    'static,
+                    Result<(SslStream<I>, Option<OwnedSemaphorePermit>), HandshakeError<I>>,
+                >,
+            >,
             service: Option<S>,
         }
     }
@@ -70,7 +87,7 @@ where
     F: Future<Output = io::Result<(I, S)>>,
     I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
-    type Output = io::Result<(SslStream<I>, S)>;
+    type Output = io::Result<(BoringSSLStream<I>, AddConnectionInfo<S>)>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let mut this = self.project();
@@ -90,14 +107,21 @@ where
                 AcceptFutureProj::InnerAccepting {
                     future,
                     handshake_timeout,
+                    connection_limit,
+                    handshake_limit,
                 } => match future.poll(cx) {
                     Poll::Ready(Ok((stream, service))) => {
                         let server_config = this.config.take().expect(
                             "config is not set. this is a bug in axum-server2, please report",
                         );
 
+                        // Snapshot the acceptor that is live right now so that in-flight
+                        // handshakes keep using it even if the certificate is rotated while
+                        // this handshake is still running.
+                        let acceptor = server_config.acceptor.load();
+
                         // Change to poll::ready(err)
-                        let ssl = match Ssl::new_from_ref(server_config.acceptor.context()) {
+                        let ssl = match Ssl::new_from_ref(acceptor.context()) {
                             Ok(ssl) => ssl,
                             Err(e) => {
                                 return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, e)))
@@ -105,8 +129,34 @@ where
                         };
 
                         let tls_builder = SslStreamBuilder::new(ssl, stream);
-                        let accept_future: BoxFuture<'_, Result<SslStream<I>, HandshakeError<I>>> =
-                            Box::pin(tls_builder.accept());
+
+                        // Gate the handshake on the configured limits:
+                        //  * the connection permit (`max_connections`) is acquired first and is
+                        //    handed back out with the stream so it lives for the whole connection;
+                        //  * the handshake permit (`max_handshake_rate`) is held only while the
+                        //    handshake runs and is dropped as soon as it resolves.
+                        // Both acquisitions run inside the `timeout` below, so they are bounded by
+                        // `handshake_timeout`.
+                        let connection_limit = connection_limit.clone();
+                        let handshake_limit = handshake_limit.clone();
+                        let accept_future: BoxFuture<
+                            '_,
+                            Result<(SslStream<I>, Option<OwnedSemaphorePermit>), HandshakeError<I>>,
+                        > = Box::pin(async move {
+                            let connection_permit = match connection_limit {
+                                Some(semaphore) => semaphore.acquire_owned().await.ok(),
+                                None => None,
+                            };
+
+                            let _handshake_permit = match handshake_limit {
+                                Some(semaphore) => semaphore.acquire_owned().await.ok(),
+                                None => None,
+                            };
+
+                            let stream = tls_builder.accept().await?;
+
+                            Ok((stream, connection_permit))
+                        });
 
                         let service = Some(service);
                         let handshake_timeout = *handshake_timeout;
@@ -123,8 +173,14 @@ where
                 },
 
                 AcceptFutureProj::TlsAccepting { future, service } => match future.poll(cx) {
-                    Poll::Ready(Ok(Ok(stream))) => {
+                    Poll::Ready(Ok(Ok((stream, permit)))) => {
                         let service = service.take().expect("future polled after ready");
+                        // Capture the negotiated ALPN protocol, version, cipher, and peer
+                        // certificate subject before handing the stream on, and carry the
+                        // connection permit so it is released when the connection is dropped.
+                        let stream = BoringSSLStream::new(stream, permit);
+                        // Surface the same info to the service so it lands in request extensions.
+                        let service = AddConnectionInfo::new(service, stream.connection_info().clone());
                         return Poll::Ready(Ok((stream, service)));
                     }
                     Poll::Ready(Ok(Err(e))) => {
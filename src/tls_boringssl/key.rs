@@ -0,0 +1,91 @@
+//! Pluggable async private-key signing for keyless TLS.
+//!
+//! For deployments that keep the private key in an HSM or a remote KMS the key never lives in a
+//! PEM file. Implement [`AsyncPrivateKey`] to perform the sign/decrypt operations out of process
+//! and pass it to
+//! [`BoringSSLConfig::with_async_private_key`](crate::tls_boringssl::BoringSSLConfig::with_async_private_key);
+//! the handshake is suspended while the operation's future runs and resumed once it resolves.
+
+use boring::error::ErrorStack;
+use boring::ssl::{SslRef, SslSignatureAlgorithm};
+use futures_util::future::BoxFuture;
+use std::sync::Arc;
+use tokio_boring::{AsyncPrivateKeyMethod, AsyncPrivateKeyMethodError};
+
+/// A private-key backend whose signing and decryption operations run asynchronously.
+///
+/// The input handed to [`sign`](AsyncPrivateKey::sign) is the digest BoringSSL wants signed with
+/// the requested [`SslSignatureAlgorithm`]; the input to [`decrypt`](AsyncPrivateKey::decrypt) is
+/// the RSA ciphertext to be decrypted. Both return the resulting bytes.
+pub trait AsyncPrivateKey: Send + Sync + 'static {
+    /// Sign `input` with `algorithm`, resolving to the signature bytes.
+    fn sign(
+        &self,
+        algorithm: SslSignatureAlgorithm,
+        input: Vec<u8>,
+    ) -> BoxFuture<'static, Result<Vec<u8>, ErrorStack>>;
+
+    /// Decrypt `input`, resolving to the plaintext bytes.
+    fn decrypt(&self, input: Vec<u8>) -> BoxFuture<'static, Result<Vec<u8>, ErrorStack>>;
+}
+
+/// Adapts an [`AsyncPrivateKey`] to tokio-boring's [`AsyncPrivateKeyMethod`] so it can be
+/// installed on an acceptor via `set_async_private_key_method`.
+pub(crate) struct AsyncPrivateKeyAdapter {
+    key: Arc<dyn AsyncPrivateKey>,
+}
+
+impl AsyncPrivateKeyAdapter {
+    pub(crate) fn new(key: Arc<dyn AsyncPrivateKey>) -> Self {
+        Self { key }
+    }
+}
+
+impl AsyncPrivateKeyMethod for AsyncPrivateKeyAdapter {
+    fn sign<'a>(
+        &self,
+        _ssl: &mut SslRef,
+        input: &[u8],
+        signature_algorithm: SslSignatureAlgorithm,
+        output: &'a mut [u8],
+    ) -> BoxFuture<'a, Result<usize, AsyncPrivateKeyMethodError>> {
+        let key = self.key.clone();
+        let input = input.to_vec();
+
+        Box::pin(async move {
+            let signature = key
+                .sign(signature_algorithm, input)
+                .await
+                .map_err(|_| AsyncPrivateKeyMethodError)?;
+
+            copy_into(&signature, output)
+        })
+    }
+
+    fn decrypt<'a>(
+        &self,
+        _ssl: &mut SslRef,
+        input: &[u8],
+        output: &'a mut [u8],
+    ) -> BoxFuture<'a, Result<usize, AsyncPrivateKeyMethodError>> {
+        let key = self.key.clone();
+        let input = input.to_vec();
+
+        Box::pin(async move {
+            let plaintext = key.decrypt(input).await.map_err(|_| AsyncPrivateKeyMethodError)?;
+
+            copy_into(&plaintext, output)
+        })
+    }
+}
+
+/// Copy `bytes` into BoringSSL's `output` buffer, returning the number of bytes written, or an
+/// error if the result does not fit.
+fn copy_into(bytes: &[u8], output: &mut [u8]) -> Result<usize, AsyncPrivateKeyMethodError> {
+    if bytes.len() > output.len() {
+        return Err(AsyncPrivateKeyMethodError);
+    }
+
+    output[..bytes.len()].copy_from_slice(bytes);
+    Ok(bytes.len())
+}
@@ -31,13 +31,43 @@ use crate::{
     accept::{Accept, DefaultAcceptor},
     server::Server,
 };
-use boring::ssl::{self, Error as BoringSSLError, SslOptions, SslVersion};
-use boring::ssl::{SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod};
-use std::{convert::TryFrom, fmt, net::SocketAddr, path::Path, sync::Arc, time::Duration};
-use tokio::io::{AsyncRead, AsyncWrite};
-use tokio_boring::SslStream;
+use boring::ssl::{self, Error as BoringSSLError, SslContext, SslOptions, SslRef, SslVersion};
+use boring::ssl::{ClientHello, SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod};
+use boring::nid::Nid;
+use arc_swap::ArcSwap;
+use futures_util::future::BoxFuture;
+use pin_project_lite::pin_project;
+use std::{
+    convert::TryFrom,
+    fmt,
+    net::SocketAddr,
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use std::io::{self, IoSlice};
+use http::Request;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_boring::{SslContextBuilderExt, SslStream};
+use tower_service::Service;
 
 pub mod future;
+pub mod key;
+pub mod session;
+
+use self::key::{AsyncPrivateKey, AsyncPrivateKeyAdapter};
+use self::session::{SessionCache, SessionCacheConfig};
+
+/// Async callback that selects the [`SslContext`] to use for a connection based on the
+/// ClientHello's SNI hostname (or `None` when the client sent no `server_name` extension).
+///
+/// Returning an error aborts the handshake with a fatal alert.
+pub type CertResolver = Arc<
+    dyn Fn(Option<&str>) -> BoxFuture<'static, Result<SslContext, BoringSSLError>> + Send + Sync,
+>;
 
 /// Create a TLS server that will be bound to the provided socket with a configuration. See
 /// the [`crate::tls_openssl`] module for more details.
@@ -54,6 +84,8 @@ pub struct BoringSSLAcceptor<A = DefaultAcceptor> {
     inner: A,
     config: BoringSSLConfig,
     handshake_timeout: Duration,
+    connection_limit: Option<Arc<Semaphore>>,
+    handshake_limit: Option<Arc<Semaphore>>,
 }
 
 impl BoringSSLAcceptor {
@@ -74,6 +106,8 @@ impl BoringSSLAcceptor {
             inner,
             config,
             handshake_timeout,
+            connection_limit: None,
+            handshake_limit: None,
         }
     }
 
@@ -82,6 +116,30 @@ impl BoringSSLAcceptor {
         self.handshake_timeout = val;
         self
     }
+
+    /// Cap the number of simultaneous accepted connections (actix's `maxconn`).
+    ///
+    /// A permit is acquired from a shared semaphore before the TLS handshake and held for the
+    /// whole lifetime of the connection (it travels with the returned [`BoringSSLStream`] and is
+    /// released when the connection is dropped). When the cap is reached new connections wait for
+    /// a permit instead of being handshaked, bounding the memory a flood of connections can pin.
+    /// The wait counts against the handshake timeout: a connection that gets no permit within it
+    /// fails with a `TimedOut` error.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.connection_limit = Some(Arc::new(Semaphore::new(max)));
+        self
+    }
+
+    /// Cap the number of TLS handshakes that may be in flight at once (actix's `maxconnrate`).
+    ///
+    /// A permit is acquired before the handshake begins and released once it finishes
+    /// (successfully, on error, or on timeout), so CPU-heavy handshakes can't all run at once.
+    /// When the cap is reached further handshakes wait for a permit instead of spinning; the wait
+    /// counts against the handshake timeout.
+    pub fn max_handshake_rate(mut self, max: usize) -> Self {
+        self.handshake_limit = Some(Arc::new(Semaphore::new(max)));
+        self
+    }
 }
 
 impl<A, I, S> Accept<I, S> for BoringSSLAcceptor<A>
@@ -89,15 +147,21 @@ where
     A: Accept<I, S>,
     A::Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
-    type Stream = SslStream<A::Stream>;
-    type Service = A::Service;
+    type Stream = BoringSSLStream<A::Stream>;
+    type Service = AddConnectionInfo<A::Service>;
     type Future = BoringSSLSSLAcceptorFuture<A::Future, A::Stream, A::Service>;
 
     fn accept(&self, stream: I, service: S) -> Self::Future {
         let inner_future = self.inner.accept(stream, service);
         let config = self.config.clone();
 
-        BoringSSLSSLAcceptorFuture::new(inner_future, config, self.handshake_timeout)
+        BoringSSLSSLAcceptorFuture::new(
+            inner_future,
+            config,
+            self.handshake_timeout,
+            self.connection_limit.clone(),
+            self.handshake_limit.clone(),
+        )
     }
 }
 
@@ -107,10 +171,182 @@ impl<A> fmt::Debug for BoringSSLAcceptor<A> {
     }
 }
 
+/// Details about a completed TLS connection, read from the handshake.
+///
+/// An instance is injected into every request's extensions by [`AddConnectionInfo`] (and is also
+/// available on the stream via [`BoringSSLStream::connection_info`]), so handlers can read it with
+/// `Request::extensions()` to make per-connection decisions such as enforcing `h2`, logging the
+/// negotiated cipher, or performing mTLS authorization.
+#[derive(Clone, Debug, Default)]
+pub struct BoringSSLConnectionInfo {
+    /// ALPN protocol negotiated for the connection (e.g. `b"h2"`), if any.
+    pub alpn_protocol: Option<Vec<u8>>,
+    /// Negotiated protocol version (e.g. `"TLSv1.3"`).
+    pub version: Option<String>,
+    /// Negotiated cipher suite name.
+    pub cipher: Option<String>,
+    /// Common name of the peer (client) certificate subject, when mTLS is in use.
+    pub peer_subject: Option<String>,
+}
+
+impl BoringSSLConnectionInfo {
+    /// Extract the connection info from a handshaked [`SslRef`].
+    pub fn from_ssl(ssl: &SslRef) -> Self {
+        let alpn_protocol = ssl.selected_alpn_protocol().map(<[u8]>::to_vec);
+        let version = Some(ssl.version_str().to_owned());
+        let cipher = ssl.current_cipher().map(|cipher| cipher.name().to_owned());
+
+        let peer_subject = ssl.peer_certificate().and_then(|cert| {
+            cert.subject_name()
+                .entries_by_nid(Nid::COMMONNAME)
+                .next()
+                .and_then(|entry| entry.data().as_utf8().ok())
+                .map(|name| name.to_string())
+        });
+
+        Self {
+            alpn_protocol,
+            version,
+            cipher,
+            peer_subject,
+        }
+    }
+}
+
+/// Service wrapper that injects the connection's [`BoringSSLConnectionInfo`] into the extensions
+/// of every request it forwards.
+///
+/// [`BoringSSLAcceptor`] wraps the per-connection service with this after the handshake, which is
+/// how the negotiated TLS parameters reach handlers through `Request::extensions()` — the same
+/// way remote-address info is surfaced.
+#[derive(Clone, Debug)]
+pub struct AddConnectionInfo<S> {
+    inner: S,
+    info: BoringSSLConnectionInfo,
+}
+
+impl<S> AddConnectionInfo<S> {
+    pub(crate) fn new(inner: S, info: BoringSSLConnectionInfo) -> Self {
+        Self { inner, info }
+    }
+}
+
+impl<S, B> Service<Request<B>> for AddConnectionInfo<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        req.extensions_mut().insert(self.info.clone());
+        self.inner.call(req)
+    }
+}
+
+pin_project! {
+    /// TLS stream produced by [`BoringSSLAcceptor`], wrapping the [`SslStream`] together with the
+    /// [`BoringSSLConnectionInfo`] gathered from the handshake.
+    ///
+    /// It transparently forwards all IO to the inner stream, so it can be used anywhere an
+    /// `AsyncRead + AsyncWrite` stream is expected.
+    pub struct BoringSSLStream<I> {
+        #[pin]
+        inner: SslStream<I>,
+        info: BoringSSLConnectionInfo,
+        // Held for the lifetime of the connection when `max_connections` is set; dropping it
+        // releases the slot back to the acceptor's semaphore.
+        permit: Option<OwnedSemaphorePermit>,
+    }
+}
+
+impl<I> BoringSSLStream<I> {
+    pub(crate) fn new(inner: SslStream<I>, permit: Option<OwnedSemaphorePermit>) -> Self {
+        let info = BoringSSLConnectionInfo::from_ssl(inner.ssl());
+
+        Self {
+            inner,
+            info,
+            permit,
+        }
+    }
+
+    /// The connection info gathered from the handshake.
+    pub fn connection_info(&self) -> &BoringSSLConnectionInfo {
+        &self.info
+    }
+
+    /// A reference to the wrapped [`SslStream`].
+    pub fn get_ref(&self) -> &SslStream<I> {
+        &self.inner
+    }
+
+    /// A mutable reference to the wrapped [`SslStream`].
+    pub fn get_mut(&mut self) -> &mut SslStream<I> {
+        &mut self.inner
+    }
+}
+
+impl<I> AsyncRead for BoringSSLStream<I>
+where
+    I: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.project().inner.poll_read(cx, buf)
+    }
+}
+
+impl<I> AsyncWrite for BoringSSLStream<I>
+where
+    I: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+}
+
 /// BoringSSL configuration.
+///
+/// The acceptor is held behind an [`ArcSwap`] so that the certificate material can be rotated
+/// at runtime (see [`reload_from_pem_file`](BoringSSLConfig::reload_from_pem_file)) while the
+/// listener keeps accepting connections. In-flight handshakes keep using the acceptor that was
+/// live when they started; new handshakes pick up the rotated material.
 #[derive(Clone)]
 pub struct BoringSSLConfig {
-    acceptor: Arc<SslAcceptor>,
+    acceptor: Arc<ArcSwap<SslAcceptor>>,
 }
 
 impl BoringSSLConfig {
@@ -128,7 +364,7 @@ impl BoringSSLConfig {
 
         tls_builder.check_private_key()?;
 
-        let acceptor = Arc::new(tls_builder.build());
+        let acceptor = Arc::new(ArcSwap::from_pointee(tls_builder.build()));
 
         Ok(BoringSSLConfig { acceptor })
     }
@@ -147,10 +383,137 @@ impl BoringSSLConfig {
 
         tls_builder.check_private_key()?;
 
-        let acceptor = Arc::new(tls_builder.build());
+        let acceptor = Arc::new(ArcSwap::from_pointee(tls_builder.build()));
 
         Ok(BoringSSLConfig { acceptor })
     }
+
+    /// Serve multiple hostnames from a single listener by selecting the certificate
+    /// asynchronously from the ClientHello's SNI.
+    ///
+    /// `resolver` is handed the requested server name (if any) as soon as the ClientHello
+    /// has been parsed and returns a future resolving to the [`SslContext`] whose certificate
+    /// and key should be used for the connection. The handshake is suspended while the future
+    /// runs and resumed once it completes, so the resolver may perform async work such as a
+    /// certificate-store lookup. The resolution is bounded by the acceptor's `handshake_timeout`.
+    ///
+    /// This enables true TLS virtual hosting.
+    pub fn with_cert_resolver(resolver: CertResolver) -> Result<Self, BoringSSLError> {
+        let mut tls_builder = default_acceptor_builder()?;
+
+        tls_builder.set_async_select_certificate_callback(move |client_hello: &mut ClientHello| {
+            let resolver = resolver.clone();
+            let server_name = client_hello.servername().map(str::to_owned);
+
+            Ok(Box::pin(async move {
+                let context = resolver(server_name.as_deref()).await?;
+
+                Ok(Box::new(move |client_hello: &mut ClientHello| {
+                    client_hello.ssl_mut().set_ssl_context(&context)?;
+                    Ok(())
+                }) as Box<_>)
+            }))
+        });
+
+        let acceptor = Arc::new(ArcSwap::from_pointee(tls_builder.build()));
+
+        Ok(BoringSSLConfig { acceptor })
+    }
+
+    /// Serve TLS with a private key that never leaves an HSM or remote KMS.
+    ///
+    /// `cert_chain` is the public certificate chain in PEM format; `key` performs the private-key
+    /// sign/decrypt operations asynchronously (see [`AsyncPrivateKey`]). The handshake is
+    /// suspended while an operation's future runs and resumed once it resolves, bounded by the
+    /// acceptor's `handshake_timeout`. This unlocks keyless TLS for this crate.
+    pub fn with_async_private_key<A: AsRef<Path>>(
+        cert_chain: A,
+        key: Arc<dyn AsyncPrivateKey>,
+    ) -> Result<Self, BoringSSLError> {
+        let mut tls_builder = default_acceptor_builder()?;
+
+        tls_builder.set_certificate_chain_file(cert_chain)?;
+
+        tls_builder.set_async_private_key_method(AsyncPrivateKeyAdapter::new(key));
+
+        let acceptor = Arc::new(ArcSwap::from_pointee(tls_builder.build()));
+
+        Ok(BoringSSLConfig { acceptor })
+    }
+
+    /// Like [`from_pem_file`](BoringSSLConfig::from_pem_file), but also enables server-side
+    /// session resumption: an external session-id cache for TLS 1.2 clients and session tickets
+    /// for TLS 1.3 clients (see [`session`] for details).
+    ///
+    /// The returned [`SessionCache`] shares storage with the acceptor and can be queried for
+    /// its hit rate; see [`session::enable_session_cache`] if you build the acceptor yourself.
+    pub fn from_pem_file_with_session_cache<A: AsRef<Path>, B: AsRef<Path>>(
+        cert: A,
+        key: B,
+        cache_config: SessionCacheConfig,
+    ) -> Result<(Self, SessionCache), BoringSSLError> {
+        let mut tls_builder = default_acceptor_builder()?;
+
+        tls_builder.set_certificate_file(cert, SslFiletype::PEM)?;
+
+        tls_builder.set_private_key_file(key, SslFiletype::PEM)?;
+
+        tls_builder.check_private_key()?;
+
+        let cache = session::enable_session_cache(&mut tls_builder, cache_config)?;
+
+        let acceptor = Arc::new(ArcSwap::from_pointee(tls_builder.build()));
+
+        Ok((BoringSSLConfig { acceptor }, cache))
+    }
+
+    /// Rotate the served certificate and key from a PEM formatted certificate and key,
+    /// without dropping the listener.
+    ///
+    /// New handshakes started after this call use the rotated material; handshakes already
+    /// in progress keep using the certificate that was live when they started. This is the
+    /// reload counterpart of [`from_pem_file`](BoringSSLConfig::from_pem_file).
+    pub fn reload_from_pem_file<A: AsRef<Path>, B: AsRef<Path>>(
+        &self,
+        cert: A,
+        key: B,
+    ) -> Result<(), BoringSSLError> {
+        let mut tls_builder = default_acceptor_builder()?;
+
+        tls_builder.set_certificate_file(cert, SslFiletype::PEM)?;
+
+        tls_builder.set_private_key_file(key, SslFiletype::PEM)?;
+
+        tls_builder.check_private_key()?;
+
+        self.acceptor.store(Arc::new(tls_builder.build()));
+
+        Ok(())
+    }
+
+    /// Rotate the served certificate and key from a PEM formatted certificate chain and key,
+    /// without dropping the listener.
+    ///
+    /// New handshakes started after this call use the rotated material; handshakes already
+    /// in progress keep using the certificate that was live when they started. This is the
+    /// reload counterpart of [`from_pem_chain_file`](BoringSSLConfig::from_pem_chain_file).
+    pub fn reload_from_pem_chain_file<A: AsRef<Path>, B: AsRef<Path>>(
+        &self,
+        chain: A,
+        key: B,
+    ) -> Result<(), BoringSSLError> {
+        let mut tls_builder = default_acceptor_builder()?;
+
+        tls_builder.set_certificate_chain_file(chain)?;
+
+        tls_builder.set_private_key_file(key, SslFiletype::PEM)?;
+
+        tls_builder.check_private_key()?;
+
+        self.acceptor.store(Arc::new(tls_builder.build()));
+
+        Ok(())
+    }
 }
 
 impl TryFrom<SslAcceptorBuilder> for BoringSSLConfig {
@@ -180,7 +543,7 @@ impl TryFrom<SslAcceptorBuilder> for BoringSSLConfig {
         // Any other checks?
         tls_builder.check_private_key()?;
 
-        let acceptor = Arc::new(tls_builder.build());
+        let acceptor = Arc::new(ArcSwap::from_pointee(tls_builder.build()));
 
         Ok(BoringSSLConfig { acceptor })
     }
@@ -0,0 +1,271 @@
+//! Server-side TLS session resumption cache.
+//!
+//! BoringSSL keeps no external cache of its own, so every reconnecting client would otherwise
+//! pay for a full handshake. [`SessionCache`] stores the sessions produced by completed
+//! handshakes in a capacity- and TTL-bounded LRU map and hands them back on resumption,
+//! cutting the asymmetric-crypto cost for returning clients. It is wired onto an
+//! [`SslAcceptorBuilder`] by [`enable_session_cache`].
+//!
+//! Two resumption mechanisms are enabled. TLS 1.2 clients (and any client sending a session id)
+//! resume through this external id cache. TLS 1.3 clients — the common modern case with the
+//! default `mozilla_intermediate_v5` builder — resume via stateless session tickets, which
+//! BoringSSL encrypts with the per-context ticket key it generates when the acceptor is built.
+//! Nothing in this module rotates that key while an acceptor is in use. A certificate
+//! [reload](crate::tls_boringssl::BoringSSLConfig::reload_from_pem_file) builds a fresh acceptor
+//! (with a fresh ticket key) without calling [`enable_session_cache`], so the cache, its
+//! callbacks and the ticket setting made here do not carry over to the reloaded acceptor.
+
+use boring::ssl::{
+    SslAcceptorBuilder, SslContextRef, SslOptions, SslRef, SslSession, SslSessionCacheMode,
+    SslSessionRef,
+};
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Tuning knobs for the server-side [`SessionCache`].
+#[derive(Clone, Copy, Debug)]
+pub struct SessionCacheConfig {
+    /// Maximum number of cached sessions before the least-recently-used entry is evicted.
+    pub capacity: usize,
+    /// How long a cached session may be reused before it is treated as expired.
+    pub ttl: Duration,
+}
+
+impl Default for SessionCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 20_480,
+            ttl: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+/// Observable counters for a [`SessionCache`], returned by [`SessionCache::metrics`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SessionCacheMetrics {
+    /// Number of resumption lookups that found a live session.
+    pub hits: u64,
+    /// Number of resumption lookups that found nothing (or an expired entry).
+    pub misses: u64,
+    /// Number of sessions stored by completed handshakes.
+    pub stored: u64,
+}
+
+impl SessionCacheMetrics {
+    /// Fraction of resumption lookups served from the cache, in `0.0..=1.0`.
+    ///
+    /// Returns `0.0` when no lookups have happened yet.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+struct Entry {
+    der: Vec<u8>,
+    stored_at: Instant,
+}
+
+/// An LRU cache of server-side TLS sessions, keyed by session id.
+///
+/// Cloning is cheap: every clone shares the same underlying storage and counters.
+#[derive(Clone)]
+pub struct SessionCache {
+    inner: Arc<SessionCacheInner>,
+}
+
+struct SessionCacheInner {
+    entries: Mutex<LruCache<Vec<u8>, Entry>>,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stored: AtomicU64,
+}
+
+impl SessionCache {
+    /// Create an empty cache with the given configuration.
+    pub fn new(config: SessionCacheConfig) -> Self {
+        let capacity = NonZeroUsize::new(config.capacity.max(1)).expect("capacity is at least 1");
+
+        let inner = SessionCacheInner {
+            entries: Mutex::new(LruCache::new(capacity)),
+            ttl: config.ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            stored: AtomicU64::new(0),
+        };
+
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Snapshot the current cache counters, primarily to observe the hit rate.
+    pub fn metrics(&self) -> SessionCacheMetrics {
+        SessionCacheMetrics {
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+            stored: self.inner.stored.load(Ordering::Relaxed),
+        }
+    }
+
+    fn insert(&self, session: &SslSessionRef) {
+        let der = match session.to_der() {
+            Ok(der) => der,
+            Err(_) => return,
+        };
+
+        self.put(session.id().to_vec(), der);
+    }
+
+    fn put(&self, id: Vec<u8>, der: Vec<u8>) {
+        let entry = Entry {
+            der,
+            stored_at: Instant::now(),
+        };
+
+        self.inner.entries.lock().unwrap().put(id, entry);
+        self.inner.stored.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get(&self, id: &[u8]) -> Option<Vec<u8>> {
+        let mut entries = self.inner.entries.lock().unwrap();
+
+        match entries.get(id) {
+            Some(entry) if entry.stored_at.elapsed() <= self.inner.ttl => {
+                let der = entry.der.clone();
+                drop(entries);
+                self.inner.hits.fetch_add(1, Ordering::Relaxed);
+                Some(der)
+            }
+            Some(_) => {
+                // Expired: drop it so we stop advertising resumption for it.
+                entries.pop(id);
+                drop(entries);
+                self.inner.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            None => {
+                drop(entries);
+                self.inner.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn remove(&self, id: &[u8]) {
+        self.inner.entries.lock().unwrap().pop(id);
+    }
+}
+
+/// Enable server-side session resumption on `builder`, returning the [`SessionCache`] handle
+/// so the caller can observe its [`metrics`](SessionCache::metrics).
+///
+/// This turns on [`SslSessionCacheMode::SERVER`], sets a session-id context (required for the
+/// external cache to resume by session id), installs the new/get/remove-session callbacks backed
+/// by the returned cache, and clears [`SslOptions::NO_TICKET`] so TLS 1.3 clients can resume via
+/// session tickets. See the [module docs](self) for how the two mechanisms divide up.
+pub fn enable_session_cache(
+    builder: &mut SslAcceptorBuilder,
+    config: SessionCacheConfig,
+) -> Result<SessionCache, boring::error::ErrorStack> {
+    let cache = SessionCache::new(config);
+
+    builder.set_session_cache_mode(SslSessionCacheMode::SERVER);
+    builder.set_session_id_context(SESSION_ID_CONTEXT)?;
+    builder.clear_options(SslOptions::NO_TICKET);
+
+    let store = cache.clone();
+    builder.set_new_session_callback(move |_ssl: &mut SslRef, session: SslSession| {
+        store.insert(&session);
+    });
+
+    let store = cache.clone();
+    builder.set_remove_session_callback(move |_ctx: &SslContextRef, session: &SslSessionRef| {
+        store.remove(session.id());
+    });
+
+    let store = cache.clone();
+    builder.set_get_session_callback(move |ssl: &mut SslRef, id: &[u8]| {
+        let der = store.get(id)?;
+
+        // SAFETY: the DER was produced by `to_der` on a session from this acceptor, so it is a
+        // well-formed session for the connection's context.
+        unsafe { SslSession::from_der(ssl.ssl_context(), &der).ok() }
+    });
+
+    Ok(cache)
+}
+
+/// Identifies sessions minted by this acceptor so the external cache only resumes its own.
+const SESSION_ID_CONTEXT: &[u8] = b"axum-server2";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(capacity: usize, ttl: Duration) -> SessionCache {
+        SessionCache::new(SessionCacheConfig { capacity, ttl })
+    }
+
+    #[test]
+    fn get_hits_and_misses_are_counted() {
+        let cache = cache(4, Duration::from_secs(60));
+
+        assert_eq!(cache.get(b"missing"), None);
+
+        cache.put(b"id".to_vec(), b"der".to_vec());
+        assert_eq!(cache.get(b"id"), Some(b"der".to_vec()));
+
+        let metrics = cache.metrics();
+        assert_eq!(metrics.stored, 1);
+        assert_eq!(metrics.hits, 1);
+        assert_eq!(metrics.misses, 1);
+        assert_eq!(metrics.hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn expired_entries_miss_and_are_evicted() {
+        let cache = cache(4, Duration::ZERO);
+
+        cache.put(b"id".to_vec(), b"der".to_vec());
+
+        // With a zero TTL any elapsed time expires the entry, so the lookup misses and the
+        // entry is dropped.
+        assert_eq!(cache.get(b"id"), None);
+        assert_eq!(cache.get(b"id"), None);
+        assert_eq!(cache.metrics().misses, 2);
+    }
+
+    #[test]
+    fn capacity_evicts_least_recently_used() {
+        let cache = cache(2, Duration::from_secs(60));
+
+        cache.put(b"a".to_vec(), b"1".to_vec());
+        cache.put(b"b".to_vec(), b"2".to_vec());
+        // Touch "a" so "b" becomes the least-recently-used entry.
+        assert_eq!(cache.get(b"a"), Some(b"1".to_vec()));
+        cache.put(b"c".to_vec(), b"3".to_vec());
+
+        assert_eq!(cache.get(b"b"), None);
+        assert_eq!(cache.get(b"a"), Some(b"1".to_vec()));
+        assert_eq!(cache.get(b"c"), Some(b"3".to_vec()));
+    }
+
+    #[test]
+    fn remove_drops_the_entry() {
+        let cache = cache(4, Duration::from_secs(60));
+
+        cache.put(b"id".to_vec(), b"der".to_vec());
+        cache.remove(b"id");
+
+        assert_eq!(cache.get(b"id"), None);
+    }
+}